@@ -1,25 +1,52 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
 
 use ropey::Rope;
 
-use crate::{movement, Change, Range, Selection, Tendril, Transaction};
+use crate::{movement, Assoc, Change, Range, Selection, Tendril, Transaction};
 use serde::{Deserialize, Serialize};
 
+/// Tab-stop markers recognized in expansion values. `$0` is the primary cursor
+/// position after expansion; `$1`, `$2` become additional cursors, turning an
+/// abbreviation into a lightweight snippet with multiple placeholders.
+const MARKERS: [&str; 3] = ["$0", "$1", "$2"];
+
 /// The type that represents the collection of abbreviations,
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct Abbreviations(HashMap<String, String>);
+pub struct Abbreviations {
+    map: HashMap<String, String>,
+    /// Per-scope abbreviation tables, keyed by e.g. language id or file extension.
+    /// Consulted before `map` so a scope can override the global table.
+    scopes: HashMap<String, HashMap<String, String>>,
+    /// Full target phrases (e.g. `"Specify Format"`) that camel-case prefixes
+    /// such as `"spFo"` can resolve against when no exact match is found in `map`.
+    targets: Vec<String>,
+}
 
 impl Abbreviations {
     pub fn default() -> Self {
-        Self(HashMap::new())
+        Self {
+            map: HashMap::new(),
+            scopes: HashMap::new(),
+            targets: Vec::new(),
+        }
     }
 
     /// Look up the word under the main cursor and trigger abbreviation for all selections if there is a match.
+    ///
+    /// `scope` is consulted first (e.g. the current document's language id), falling
+    /// back to the global table and then camel-case prefix resolution.
     pub fn expand_or_insert(
         &self,
         doc: &Rope,
         selection: &Selection,
         c: char,
+        scope: Option<&str>,
     ) -> Option<Transaction> {
         // Default function to insert the original char when we should not expand an abbreviation
         fn insert(c: char, cursor: usize) -> Change {
@@ -28,11 +55,19 @@ impl Abbreviations {
             (cursor, cursor, Some(t))
         }
 
+        // Per original range, in selection order: the old cursor position (`to`
+        // of the change), and, for ranges that expanded into a marked snippet,
+        // the char offset of the insertion start (`from` of the change) plus
+        // the char offset of each tab-stop marker within the inserted text.
+        let per_range: RefCell<Vec<(usize, Option<(usize, Vec<usize>)>)>> =
+            RefCell::new(Vec::new());
+
         let transaction = Transaction::change_by_selection(doc, selection, |range| {
             let cursor = range.cursor(doc.slice(..));
 
             // Do not look for previous word at start of file
             if cursor == 0 {
+                per_range.borrow_mut().push((cursor, None));
                 return insert(c, cursor);
             }
 
@@ -47,42 +82,180 @@ impl Abbreviations {
 
             // Early return. Abbreviation should have at least 2 characters
             if current_word_range.len() < 1 {
+                per_range.borrow_mut().push((cursor, None));
                 return insert(c, cursor);
             }
 
             // Get current word and check if we know it as an abbreviation
             let current_word = doc.slice(current_word_range.head..current_word_range.anchor);
-            let whole_word = self.0.get(&current_word.to_string());
+            let current_word = current_word.to_string();
+            let whole_word = scope
+                .and_then(|scope| self.scopes.get(scope))
+                .and_then(|scoped| scoped.get(&current_word))
+                .or_else(|| self.map.get(&current_word))
+                .cloned()
+                .or_else(|| self.resolve_camel_prefix(&current_word));
 
             // Expand abbreviation if needed, insert the original char otherwise
             match whole_word {
                 Some(w) => {
+                    let from = current_word_range.cursor(doc.slice(..));
+                    let (expansion, marker_offsets) = strip_markers(&w);
+
                     let mut t = Tendril::new();
-                    t.push_str(w);
+                    t.push_str(&expansion);
                     t.push(c);
-                    (current_word_range.cursor(doc.slice(..)), cursor, Some(t))
+
+                    let marker_data = (!marker_offsets.is_empty()).then_some((from, marker_offsets));
+                    per_range.borrow_mut().push((cursor, marker_data));
+
+                    (from, cursor, Some(t))
+                }
+                None => {
+                    per_range.borrow_mut().push((cursor, None));
+                    insert(c, cursor)
+                }
+            }
+        });
+
+        let per_range = per_range.into_inner();
+        if per_range.iter().all(|(_, marker_data)| marker_data.is_none()) {
+            return Some(transaction);
+        }
+
+        // Rebuild the selection range-by-range: ranges with no marker keep the
+        // usual post-insertion cursor (the old cursor position mapped forward
+        // through the edit), while ranges that expanded into a marked snippet
+        // get one range per tab stop instead, `$0` first.
+        let changes = transaction.changes();
+        let mut ranges = Vec::new();
+        let mut primary_index = 0;
+        for (i, (cursor, marker_data)) in per_range.iter().enumerate() {
+            let start = ranges.len();
+            match marker_data {
+                Some((from, offsets)) => {
+                    let new_from = changes.map_pos(*from, Assoc::Before);
+                    ranges.extend(offsets.iter().map(|offset| Range::point(new_from + offset)));
                 }
-                None => insert(c, cursor),
+                None => ranges.push(Range::point(changes.map_pos(*cursor, Assoc::After))),
+            }
+            if i == selection.primary_index() {
+                primary_index = start;
+            }
+        }
+
+        Some(transaction.with_selection(Selection::new(ranges, primary_index)))
+    }
+
+    /// Try to resolve `abbr` as a camel-case prefix concatenation of one of the
+    /// registered target phrases, e.g. `"doAp"` against `"DOJ Appointment"`.
+    ///
+    /// Each segment of the abbreviation (split at uppercase boundaries, lowercased)
+    /// must be a prefix of the corresponding word of the target (split on
+    /// whitespace, lowercased). Resolution only succeeds if exactly one target
+    /// matches, so expansion stays unambiguous.
+    fn resolve_camel_prefix(&self, abbr: &str) -> Option<String> {
+        let segments = split_camel_case(abbr);
+        if segments.is_empty() {
+            return None;
+        }
+
+        let mut matches = self.targets.iter().filter(|target| {
+            let words: Vec<&str> = target.split_whitespace().collect();
+            if segments.len() > words.len() {
+                return false;
             }
+            segments
+                .iter()
+                .zip(words.iter())
+                .all(|(segment, word)| word.to_lowercase().starts_with(segment.as_str()))
         });
-        Some(transaction)
+
+        match (matches.next(), matches.next()) {
+            (Some(target), None) => Some(target.clone()),
+            _ => None,
+        }
     }
 
     pub fn insert(&mut self, abbr: &str, whole_word: &str) {
-        self.0.insert(abbr.to_string(), whole_word.to_string());
+        self.map.insert(abbr.to_string(), whole_word.to_string());
+    }
+
+    /// Register an abbreviation that only applies within `scope` (e.g. a language id).
+    pub fn insert_scoped(&mut self, scope: &str, abbr: &str, whole_word: &str) {
+        self.scopes
+            .entry(scope.to_string())
+            .or_default()
+            .insert(abbr.to_string(), whole_word.to_string());
+    }
+
+    /// Remove an abbreviation previously registered for `scope`.
+    pub fn remove_scoped(&mut self, scope: &str, key: &str) {
+        if let Some(scoped) = self.scopes.get_mut(scope) {
+            scoped.remove(key);
+        }
+    }
+
+    /// Register a full target phrase that camel-case prefixes can resolve against.
+    pub fn insert_target(&mut self, target: &str) {
+        self.targets.push(target.to_string());
     }
 
     pub fn map(&self) -> &HashMap<String, String> {
-        &self.0
+        &self.map
     }
 
     pub fn map_mut(&mut self) -> &mut HashMap<String, String> {
-        &mut self.0
+        &mut self.map
     }
 
     pub fn remove(&mut self, key: &str) {
-        self.0.remove(key);
+        self.map.remove(key);
+    }
+}
+
+/// Strip `$0`/`$1`/`$2` tab-stop markers out of an expansion value, returning the
+/// marker-free text along with the char offset of each marker found, relative to
+/// the start of the returned text. `$0` is always recorded first (the primary
+/// cursor), regardless of where it appears in `text`, followed by `$1` then `$2`
+/// in the order they occur.
+fn strip_markers(text: &str) -> (String, Vec<usize>) {
+    let mut stripped = String::with_capacity(text.len());
+    let mut found: Vec<(usize, usize)> = Vec::new(); // (marker rank, char offset)
+    let mut rest = text;
+
+    while let Some((marker_pos, rank, marker)) = MARKERS
+        .iter()
+        .enumerate()
+        .filter_map(|(rank, marker)| rest.find(marker).map(|pos| (pos, rank, *marker)))
+        .min_by_key(|(pos, _, _)| *pos)
+    {
+        stripped.push_str(&rest[..marker_pos]);
+        found.push((rank, stripped.chars().count()));
+        rest = &rest[marker_pos + marker.len()..];
     }
+    stripped.push_str(rest);
+
+    found.sort_by_key(|(rank, _)| *rank);
+    (stripped, found.into_iter().map(|(_, offset)| offset).collect())
+}
+
+/// Split a camel-case abbreviation into its lowercase segments, e.g. `"spFo"` -> `["sp", "fo"]`.
+fn split_camel_case(abbr: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+
+    for ch in abbr.chars() {
+        if ch.is_uppercase() && !current.is_empty() {
+            segments.push(std::mem::take(&mut current));
+        }
+        current.extend(ch.to_lowercase());
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+
+    segments
 }
 
 impl From<&PathBuf> for Abbreviations {
@@ -90,13 +263,224 @@ impl From<&PathBuf> for Abbreviations {
         let mut abbr = Self::default();
 
         if let Ok(abbr_file_content) = std::fs::read_to_string(value) {
-            // Each line should insert an abbr
+            // Each line should insert an abbr, or register a target phrase if
+            // prefixed with `~`. A `[scope]` header routes subsequent entries
+            // into that scope until the next header (or end of file).
+            let mut scope: Option<&str> = None;
             for line in abbr_file_content.lines() {
-                if let Some(split) = line.split_once(' ') {
-                    abbr.insert(split.0, split.1);
+                if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                    scope = Some(header);
+                } else if let Some(target) = line.strip_prefix('~') {
+                    abbr.insert_target(target);
+                } else if let Some(split) = line.split_once(' ') {
+                    match scope {
+                        Some(scope) => abbr.insert_scoped(scope, split.0, split.1),
+                        None => abbr.insert(split.0, split.1),
+                    }
                 }
             }
         }
         abbr
     }
 }
+
+/// How an [`AbbreviationsCache`] should treat a previously parsed abbreviations file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStrategy {
+    /// Never cache: always re-read and re-parse the file from disk.
+    None,
+    /// Reparse only when the file's mtime changed since it was last cached.
+    Mtime,
+    /// Parse once and keep serving the same value, ignoring later edits.
+    Pinned,
+}
+
+/// Caches parsed [`Abbreviations`] by path so that documents/selections sharing
+/// the same abbreviations file don't re-read and re-parse it on every lookup.
+#[derive(Debug, Default)]
+pub struct AbbreviationsCache {
+    entries: Mutex<HashMap<PathBuf, (Option<SystemTime>, Arc<Abbreviations>)>>,
+}
+
+impl AbbreviationsCache {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the abbreviations for `path`, applying `strategy` to decide whether
+    /// a cached value may be reused.
+    pub fn get(&self, path: &Path, strategy: CacheStrategy) -> Arc<Abbreviations> {
+        if strategy == CacheStrategy::None {
+            return Arc::new(Abbreviations::from(&path.to_path_buf()));
+        }
+
+        let mtime = std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+
+        if let Some(abbr) = self.try_reuse(path, mtime, strategy) {
+            return abbr;
+        }
+
+        // Parse outside the lock: a blocking read of one abbreviations file
+        // should not stall lookups for every other cached path.
+        let abbr = Arc::new(Abbreviations::from(&path.to_path_buf()));
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(path.to_path_buf(), (mtime, Arc::clone(&abbr)));
+        abbr
+    }
+
+    fn try_reuse(
+        &self,
+        path: &Path,
+        mtime: Option<SystemTime>,
+        strategy: CacheStrategy,
+    ) -> Option<Arc<Abbreviations>> {
+        let entries = self.entries.lock().unwrap();
+        let (cached_mtime, abbr) = entries.get(path)?;
+        let reuse = match strategy {
+            CacheStrategy::Pinned => true,
+            // Reparse whenever the mtime is different in either direction, and
+            // treat "no mtime available" (e.g. the file didn't exist yet) as
+            // stable so a missing path isn't reparsed on every single call.
+            CacheStrategy::Mtime => mtime == *cached_mtime,
+            CacheStrategy::None => unreachable!(),
+        };
+        reuse.then(|| Arc::clone(abbr))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolves_unique_camel_prefix() {
+        let mut abbr = Abbreviations::default();
+        abbr.insert_target("Specify Format");
+        abbr.insert_target("Specify Filename");
+
+        assert_eq!(
+            abbr.resolve_camel_prefix("spFo"),
+            Some("Specify Format".to_string())
+        );
+    }
+
+    #[test]
+    fn ambiguous_camel_prefix_does_not_resolve() {
+        let mut abbr = Abbreviations::default();
+        abbr.insert_target("Specify Format");
+        abbr.insert_target("Specify Folder");
+
+        assert_eq!(abbr.resolve_camel_prefix("spFo"), None);
+    }
+
+    #[test]
+    fn unmatched_camel_prefix_does_not_resolve() {
+        let mut abbr = Abbreviations::default();
+        abbr.insert_target("Specify Format");
+
+        assert_eq!(abbr.resolve_camel_prefix("doAp"), None);
+    }
+
+    #[test]
+    fn mtime_cache_reparses_when_file_changes_in_either_direction() {
+        use std::time::Duration;
+
+        let path = std::env::temp_dir().join(format!(
+            "helix-abbreviations-cache-test-{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&path, "foo bar\n").unwrap();
+        let base_mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        let cache = AbbreviationsCache::new();
+        let first = cache.get(&path, CacheStrategy::Mtime);
+        assert_eq!(first.map().get("foo"), Some(&"bar".to_string()));
+
+        // A strictly newer mtime invalidates the cache.
+        std::fs::write(&path, "foo baz\n").unwrap();
+        let newer = base_mtime + Duration::from_secs(5);
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(newer)
+            .unwrap();
+        let second = cache.get(&path, CacheStrategy::Mtime);
+        assert_eq!(second.map().get("foo"), Some(&"baz".to_string()));
+
+        // An older mtime (clock skew, restoring a backup, ...) must also
+        // invalidate the cache rather than being treated as "unchanged".
+        std::fs::write(&path, "foo qux\n").unwrap();
+        let older = newer - Duration::from_secs(60);
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(older)
+            .unwrap();
+        let third = cache.get(&path, CacheStrategy::Mtime);
+        assert_eq!(third.map().get("foo"), Some(&"qux".to_string()));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn mtime_cache_treats_missing_file_as_stable() {
+        let path = std::env::temp_dir().join(format!(
+            "helix-abbreviations-cache-test-missing-{}.txt",
+            std::process::id()
+        ));
+        std::fs::remove_file(&path).ok();
+
+        let cache = AbbreviationsCache::new();
+        let first = cache.get(&path, CacheStrategy::Mtime);
+        let second = cache.get(&path, CacheStrategy::Mtime);
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn scope_takes_precedence_over_global() {
+        let mut abbr = Abbreviations::default();
+        abbr.insert("foo", "global");
+        abbr.insert_scoped("rust", "foo", "scoped");
+
+        let doc = Rope::from_str("foo");
+        let selection = Selection::new(vec![Range::point(3)], 0);
+
+        let transaction = abbr
+            .expand_or_insert(&doc, &selection, ' ', Some("rust"))
+            .unwrap();
+        let mut scoped_doc = doc.clone();
+        transaction.apply(&mut scoped_doc);
+        assert_eq!(scoped_doc.to_string(), "scoped ");
+
+        let transaction = abbr.expand_or_insert(&doc, &selection, ' ', None).unwrap();
+        let mut global_doc = doc.clone();
+        transaction.apply(&mut global_doc);
+        assert_eq!(global_doc.to_string(), "global ");
+    }
+
+    #[test]
+    fn mixed_marked_and_unmarked_expansion_keeps_every_cursor() {
+        let mut abbr = Abbreviations::default();
+        abbr.insert("fn", "fn $0() {}");
+        abbr.insert("hi", "hello");
+
+        let doc = Rope::from_str("fn hi");
+        // Cursor 0 sits right after "fn" (triggers a marked snippet), cursor 1
+        // right after "hi" (a plain, marker-free expansion).
+        let selection = Selection::new(vec![Range::point(2), Range::point(5)], 0);
+
+        let transaction = abbr.expand_or_insert(&doc, &selection, ' ', None).unwrap();
+
+        // Both cursors must survive the repositioning, not just the marked one.
+        let result_selection = transaction.selection().unwrap();
+        assert_eq!(result_selection.len(), 2);
+
+        let mut text = doc.clone();
+        transaction.apply(&mut text);
+        assert_eq!(text.to_string(), "fn () {}  hello ");
+    }
+}